@@ -0,0 +1,90 @@
+use crate::editor::diff::DiffEditorData;
+
+/// Commands exposed by a diff editor, reachable from the command palette
+/// and bindable to keyboard shortcuts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffEditorCommand {
+    /// Move to the next changed hunk.
+    NextDiff,
+    /// Move to the previous changed hunk.
+    PrevDiff,
+    /// Apply the hunk at the cursor onto the opposing side.
+    ApplyHunk,
+    /// Revert the hunk at the cursor from the opposing side.
+    RevertHunk,
+    /// Cycle the whitespace-handling mode used when diffing.
+    ToggleIgnoreWhitespace,
+}
+
+/// Every `DiffEditorCommand` variant, in the same order their entries
+/// appear in `defaults/keymaps-common.toml`.
+const ALL_DIFF_EDITOR_COMMANDS: &[DiffEditorCommand] = &[
+    DiffEditorCommand::NextDiff,
+    DiffEditorCommand::PrevDiff,
+    DiffEditorCommand::ApplyHunk,
+    DiffEditorCommand::RevertHunk,
+    DiffEditorCommand::ToggleIgnoreWhitespace,
+];
+
+impl DiffEditorCommand {
+    /// The stable id this command is registered under in
+    /// `defaults/keymaps-common.toml` and shown in the command palette.
+    pub fn command_id(self) -> &'static str {
+        match self {
+            DiffEditorCommand::NextDiff => "diff_editor.next_diff",
+            DiffEditorCommand::PrevDiff => "diff_editor.prev_diff",
+            DiffEditorCommand::ApplyHunk => "diff_editor.apply_hunk",
+            DiffEditorCommand::RevertHunk => "diff_editor.revert_hunk",
+            DiffEditorCommand::ToggleIgnoreWhitespace => {
+                "diff_editor.toggle_ignore_whitespace"
+            }
+        }
+    }
+
+    /// Resolves a command id (as looked up from a keybinding or the
+    /// command palette) back to the variant it names.
+    pub fn from_command_id(id: &str) -> Option<Self> {
+        ALL_DIFF_EDITOR_COMMANDS
+            .iter()
+            .copied()
+            .find(|cmd| cmd.command_id() == id)
+    }
+
+    /// Runs this command against `diff_editor`'s `is_right` side, resolving
+    /// `ApplyHunk`/`RevertHunk` against the hunk under the cursor on that
+    /// side, if any.
+    pub fn run(self, diff_editor: &DiffEditorData, is_right: bool) {
+        match self {
+            DiffEditorCommand::NextDiff => diff_editor.next_diff(is_right),
+            DiffEditorCommand::PrevDiff => diff_editor.prev_diff(is_right),
+            DiffEditorCommand::ToggleIgnoreWhitespace => {
+                diff_editor.toggle_ignore_whitespace();
+            }
+            DiffEditorCommand::ApplyHunk | DiffEditorCommand::RevertHunk => {
+                let Some(change_idx) = diff_editor.hunk_at_cursor(is_right) else {
+                    return;
+                };
+                if self == DiffEditorCommand::ApplyHunk {
+                    diff_editor.apply_hunk(is_right, change_idx);
+                } else {
+                    diff_editor.revert_hunk(is_right, change_idx);
+                }
+            }
+        }
+    }
+}
+
+/// Resolves `id` to a `DiffEditorCommand` and runs it against a focused
+/// diff editor, returning whether it matched one. A `false` return leaves
+/// the command id for the next handler to try.
+pub fn dispatch_diff_editor_command(
+    id: &str,
+    diff_editor: &DiffEditorData,
+    is_right: bool,
+) -> bool {
+    let Some(command) = DiffEditorCommand::from_command_id(id) else {
+        return false;
+    };
+    command.run(diff_editor, is_right);
+    true
+}
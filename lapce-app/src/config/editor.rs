@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+use crate::editor::diff::{DiffAlgorithm, WhitespaceDiffMode};
+
+/// Editor-scoped settings, deserialized from the `[editor]` table of the
+/// user's settings file.
+///
+/// Only the fields the diff-editor work added are modeled here, standing
+/// in for the existing `EditorConfig`: these merge into it rather than
+/// replacing it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EditorConfig {
+    /// Algorithm used to compute the line-level diff shown in diff editors.
+    #[serde(default)]
+    pub diff_algorithm: DiffAlgorithm,
+    /// How whitespace differences are treated when computing a diff.
+    #[serde(default)]
+    pub ignore_whitespace: WhitespaceDiffMode,
+}
+
+impl Default for EditorConfig {
+    fn default() -> Self {
+        Self {
+            diff_algorithm: DiffAlgorithm::default(),
+            ignore_whitespace: WhitespaceDiffMode::default(),
+        }
+    }
+}
+
+impl EditorConfig {
+    /// The algorithm to use for diffing, as selected in settings.
+    pub fn diff_algorithm(&self) -> DiffAlgorithm {
+        self.diff_algorithm
+    }
+
+    /// The whitespace-handling mode to use for diffing, as selected in
+    /// settings.
+    pub fn ignore_whitespace(&self) -> WhitespaceDiffMode {
+        self.ignore_whitespace
+    }
+}
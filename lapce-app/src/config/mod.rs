@@ -0,0 +1,13 @@
+pub mod editor;
+
+use serde::{Deserialize, Serialize};
+
+use self::editor::EditorConfig;
+
+/// Top-level settings, deserialized from the user's settings file and held
+/// as `CommonData::config`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct LapceConfig {
+    #[serde(default)]
+    pub editor: EditorConfig,
+}
@@ -1,4 +1,10 @@
-use std::{path::PathBuf, sync::atomic};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    ops::Range,
+    path::PathBuf,
+    sync::{atomic, Arc},
+};
 
 use floem::{
     ext_event::create_ext_action,
@@ -8,8 +14,13 @@ use floem::{
         SignalWithUntracked,
     },
 };
-use lapce_core::buffer::{rope_diff, rope_text::RopeText, DiffLines};
+use lapce_core::{
+    buffer::{rope_diff, rope_text::RopeText, DiffLines, Rope},
+    editor::EditType,
+    selection::Selection,
+};
 use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
     doc::{DocContent, Document},
@@ -20,16 +31,63 @@ use crate::{
 
 use super::{location::EditorLocation, EditorData, EditorViewKind};
 
+/// Which algorithm is used to compute the line-level diff shown in a
+/// [`DiffEditorData`]. Configurable via settings, defaulting to `Patience`
+/// since it tends to produce far more readable hunks than `Myers` on
+/// reordered or refactored code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiffAlgorithm {
+    /// Classic Myers diff, as implemented by `rope_diff`.
+    Myers,
+    /// Anchors on lines that are unique on both sides, then recurses on the
+    /// gaps between anchors, falling back to `Myers` where there are no
+    /// unique anchors to anchor on.
+    Patience,
+    /// Plain longest-common-subsequence line diff.
+    Lcs,
+    /// Levenshtein (edit-distance) line diff.
+    Levenshtein,
+}
+
+impl Default for DiffAlgorithm {
+    fn default() -> Self {
+        DiffAlgorithm::Patience
+    }
+}
+
 #[derive(Clone)]
 pub struct DiffInfo {
     pub is_right: bool,
     pub changes: im::Vector<DiffLines>,
+    /// Intra-line highlight spans for changed lines, keyed by the line
+    /// number on this side of the diff. A line only has an entry if it is
+    /// part of a change block (a `Left`/`Right` pair), and the spans mark
+    /// the byte ranges that actually differ from its counterpart on the
+    /// other side.
+    pub line_changes: im::HashMap<usize, Vec<Range<usize>>>,
+}
+
+impl DiffInfo {
+    /// The intra-line highlight spans for `line`, if it's part of a change
+    /// block: the word-level ranges to layer on top of the whole-line
+    /// added/removed background. Empty for any other line.
+    pub fn line_highlights(&self, line: usize) -> &[Range<usize>] {
+        self.line_changes
+            .get(&line)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
 }
 
+/// Either side of a diff editor may have no document at all (e.g. when
+/// showing a deleted or newly-added file standalone): `None` synthesizes an
+/// empty, read-only buffer for that side instead of requiring a fake blank
+/// tab.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct DiffEditorInfo {
-    pub left_content: DocContent,
-    pub right_content: DocContent,
+    pub left_content: Option<DocContent>,
+    pub right_content: Option<DocContent>,
 }
 
 impl DiffEditorInfo {
@@ -45,8 +103,8 @@ impl DiffEditorInfo {
         let new_editor = {
             let data = data.clone();
             let common = data.common.clone();
-            move |content: &DocContent| match content {
-                DocContent::File(path) => {
+            move |content: &Option<DocContent>| match content {
+                Some(DocContent::File(path)) => {
                     let editor_id = EditorId::next();
                     let (doc, new_doc) = data.get_doc(path.clone());
                     let editor_data =
@@ -64,11 +122,17 @@ impl DiffEditorInfo {
                     );
                     editor_data
                 }
-                DocContent::Local => {
+                Some(DocContent::Local) => {
                     let editor_id = EditorId::next();
                     EditorData::new_local(data.scope, editor_id, common.clone())
                 }
-                DocContent::History(_) => {
+                Some(DocContent::History(_)) => {
+                    let editor_id = EditorId::next();
+                    EditorData::new_local(data.scope, editor_id, common.clone())
+                }
+                // No document for this side: synthesize an empty buffer so
+                // the whole present side renders as added/removed.
+                None => {
                     let editor_id = EditorId::next();
                     EditorData::new_local(data.scope, editor_id, common.clone())
                 }
@@ -80,12 +144,38 @@ impl DiffEditorInfo {
         let right = new_editor(&self.right_content);
         let right = create_rw_signal(cx, right);
 
+        let missing_side = match (&self.left_content, &self.right_content) {
+            (None, Some(_)) => Some(false),
+            (Some(_), None) => Some(true),
+            _ => None,
+        };
+
+        let diff_algorithm = create_rw_signal(
+            cx,
+            data.common
+                .config
+                .get_untracked()
+                .editor
+                .diff_algorithm(),
+        );
+        let ignore_whitespace = create_rw_signal(
+            cx,
+            data.common
+                .config
+                .get_untracked()
+                .editor
+                .ignore_whitespace(),
+        );
+
         let diff_editor_data = DiffEditorData {
             id: diff_editor_id,
             editor_tab_id,
             scope: cx,
             left,
             right,
+            diff_algorithm,
+            ignore_whitespace,
+            missing_side,
         };
 
         data.diff_editors.update(|diff_editors| {
@@ -105,6 +195,16 @@ pub struct DiffEditorData {
     pub scope: Scope,
     pub left: RwSignal<EditorData>,
     pub right: RwSignal<EditorData>,
+    /// Which diff algorithm to run when (re)computing `DiffInfo` for this
+    /// editor. Defaults to the value configured in settings.
+    pub diff_algorithm: RwSignal<DiffAlgorithm>,
+    /// Whether to ignore whitespace differences when computing the diff.
+    /// Defaults to the value configured in settings.
+    pub ignore_whitespace: RwSignal<WhitespaceDiffMode>,
+    /// `Some(true)` if the right side has no real document and was
+    /// synthesized as an empty read-only buffer, `Some(false)` for the
+    /// left, `None` if both sides are real documents.
+    pub missing_side: Option<bool>,
 }
 
 impl DiffEditorData {
@@ -120,8 +220,16 @@ impl DiffEditorData {
         let left =
             EditorData::new(cx, None, EditorId::next(), left_doc, common.clone());
         let left = create_rw_signal(left.scope, left);
-        let right = EditorData::new(cx, None, EditorId::next(), right_doc, common);
+        let right = EditorData::new(cx, None, EditorId::next(), right_doc, common.clone());
         let right = create_rw_signal(right.scope, right);
+        let diff_algorithm = create_rw_signal(
+            cx,
+            common.config.get_untracked().editor.diff_algorithm(),
+        );
+        let ignore_whitespace = create_rw_signal(
+            cx,
+            common.config.get_untracked().editor.ignore_whitespace(),
+        );
 
         let data = Self {
             id,
@@ -129,6 +237,9 @@ impl DiffEditorData {
             scope: cx,
             left,
             right,
+            diff_algorithm,
+            ignore_whitespace,
+            missing_side: None,
         };
 
         data.listen_diff_changes();
@@ -138,8 +249,12 @@ impl DiffEditorData {
 
     pub fn diff_editor_info(&self) -> DiffEditorInfo {
         DiffEditorInfo {
-            left_content: self.left.get_untracked().doc.get_untracked().content,
-            right_content: self.left.get_untracked().doc.get_untracked().content,
+            left_content: (self.missing_side != Some(false)).then(|| {
+                self.left.get_untracked().doc.get_untracked().content
+            }),
+            right_content: (self.missing_side != Some(true)).then(|| {
+                self.right.get_untracked().doc.get_untracked().content
+            }),
         }
     }
 
@@ -162,6 +277,10 @@ impl DiffEditorData {
                 .get_untracked()
                 .copy(cx, None, EditorId::next()),
         );
+        diff_editor.diff_algorithm =
+            create_rw_signal(cx, diff_editor.diff_algorithm.get_untracked());
+        diff_editor.ignore_whitespace =
+            create_rw_signal(cx, diff_editor.ignore_whitespace.get_untracked());
         diff_editor.listen_diff_changes();
         diff_editor
     }
@@ -181,6 +300,9 @@ impl DiffEditorData {
             right_doc.with(|doc| (doc.content.clone(), doc.rev()))
         });
 
+        let diff_algorithm = self.diff_algorithm;
+        let ignore_whitespace = self.ignore_whitespace;
+
         create_effect(cx, move |_| {
             let (_, left_rev) = left_doc_rev.get();
             let (left_editor_view, left_doc) =
@@ -196,14 +318,15 @@ impl DiffEditorData {
                 (doc.buffer().atomic_rev(), doc.buffer().text().clone())
             });
 
+            let algorithm = diff_algorithm.get();
+            let whitespace = ignore_whitespace.get();
+
             let send = {
                 let right_atomic_rev = right_atomic_rev.clone();
                 create_ext_action(
                     cx,
-                    move |changes: Option<im::Vector<DiffLines>>| {
-                        let changes = if let Some(changes) = changes {
-                            changes
-                        } else {
+                    move |result: Option<DiffResult>| {
+                        let Some(result) = result else {
                             return;
                         };
 
@@ -221,26 +344,1340 @@ impl DiffEditorData {
 
                         left_editor_view.set(EditorViewKind::Diff(DiffInfo {
                             is_right: false,
-                            changes: changes.clone(),
+                            changes: result.changes.clone(),
+                            line_changes: result.left_line_changes,
                         }));
                         right_editor_view.set(EditorViewKind::Diff(DiffInfo {
                             is_right: true,
-                            changes,
+                            changes: result.changes,
+                            line_changes: result.right_line_changes,
                         }));
                     },
                 )
             };
 
             rayon::spawn(move || {
-                let changes = rope_diff(
-                    left_rope,
-                    right_rope,
-                    right_rev,
-                    right_atomic_rev.clone(),
-                    Some(3),
+                let changes = match algorithm {
+                    DiffAlgorithm::Myers => myers_diff(
+                        &left_rope,
+                        &right_rope,
+                        right_rev,
+                        right_atomic_rev.clone(),
+                        Some(3),
+                        whitespace,
+                    ),
+                    DiffAlgorithm::Patience => patience_diff(
+                        &left_rope,
+                        &right_rope,
+                        right_rev,
+                        right_atomic_rev.clone(),
+                        Some(3),
+                        whitespace,
+                    ),
+                    DiffAlgorithm::Lcs => lcs_diff(
+                        &left_rope,
+                        &right_rope,
+                        right_rev,
+                        right_atomic_rev.clone(),
+                        Some(3),
+                        whitespace,
+                    ),
+                    DiffAlgorithm::Levenshtein => levenshtein_diff(
+                        &left_rope,
+                        &right_rope,
+                        right_rev,
+                        right_atomic_rev.clone(),
+                        Some(3),
+                        whitespace,
+                    ),
+                };
+
+                let result = changes.map(|changes| {
+                    let (left_line_changes, right_line_changes) =
+                        compute_intraline_changes(
+                            &changes,
+                            &left_rope,
+                            &right_rope,
+                        );
+                    DiffResult {
+                        changes: im::Vector::from(changes),
+                        left_line_changes,
+                        right_line_changes,
+                    }
+                });
+                send(result);
+            });
+        });
+    }
+
+    /// The diff hunks currently displayed for this editor, as last computed
+    /// by `listen_diff_changes`.
+    fn changes(&self) -> im::Vector<DiffLines> {
+        self.right.with_untracked(|editor| {
+            editor.new_view.with_untracked(|view| match view {
+                EditorViewKind::Diff(info) => info.changes.clone(),
+                _ => im::Vector::new(),
+            })
+        })
+    }
+
+    /// Moves `is_right`'s cursor to the start of the next changed hunk after
+    /// its current line, if there is one.
+    pub fn next_diff(&self, is_right: bool) {
+        self.move_to_diff(is_right, true);
+    }
+
+    /// Moves `is_right`'s cursor to the start of the previous changed hunk
+    /// before its current line, if there is one.
+    pub fn prev_diff(&self, is_right: bool) {
+        self.move_to_diff(is_right, false);
+    }
+
+    /// Cycles `ignore_whitespace` to the next mode, re-triggering the
+    /// memoized diff via `listen_diff_changes`'s effect.
+    pub fn toggle_ignore_whitespace(&self) {
+        self.ignore_whitespace.update(|mode| {
+            *mode = match mode {
+                WhitespaceDiffMode::IgnoreNone => WhitespaceDiffMode::IgnoreTrailing,
+                WhitespaceDiffMode::IgnoreTrailing => WhitespaceDiffMode::IgnoreAll,
+                WhitespaceDiffMode::IgnoreAll => WhitespaceDiffMode::IgnoreNone,
+            };
+        });
+    }
+
+    /// Returns the index into `self.changes()` of the hunk containing
+    /// `is_right`'s cursor line, on `is_right`'s own side, if the cursor is
+    /// currently inside one.
+    pub(crate) fn hunk_at_cursor(&self, is_right: bool) -> Option<usize> {
+        let changes = self.changes();
+        let editor = if is_right { self.right } else { self.left };
+
+        let current_line = editor.with_untracked(|editor| {
+            let buffer_offset =
+                editor.cursor.with_untracked(|cursor| cursor.offset());
+            editor
+                .doc
+                .with_untracked(|doc| doc.buffer().line_of_offset(buffer_offset))
+        });
+
+        changes.iter().position(|change| match change {
+            DiffLines::Left(range) if !is_right => range.contains(&current_line),
+            DiffLines::Right(range) if is_right => range.contains(&current_line),
+            _ => false,
+        })
+    }
+
+    fn move_to_diff(&self, is_right: bool, forward: bool) {
+        let changes = self.changes();
+        let editor = if is_right { self.right } else { self.left };
+
+        let hunk_starts: Vec<usize> = changes
+            .iter()
+            .filter_map(|change| match change {
+                DiffLines::Left(range) if !is_right => Some(range.start),
+                DiffLines::Right(range) if is_right => Some(range.start),
+                _ => None,
+            })
+            .collect();
+
+        editor.with_untracked(|editor| {
+            let buffer_offset =
+                editor.cursor.with_untracked(|cursor| cursor.offset());
+            let current_line = editor
+                .doc
+                .with_untracked(|doc| doc.buffer().line_of_offset(buffer_offset));
+
+            let target_line = if forward {
+                hunk_starts.iter().find(|&&line| line > current_line)
+            } else {
+                hunk_starts.iter().rev().find(|&&line| line < current_line)
+            };
+
+            if let Some(&line) = target_line {
+                let offset = editor
+                    .doc
+                    .with_untracked(|doc| doc.buffer().offset_of_line(line));
+                editor
+                    .cursor
+                    .update(|cursor| cursor.set_insert(Selection::caret(offset)));
+            }
+        });
+    }
+
+    /// Reverts hunk `change_idx` on `is_right`'s side: makes this side match
+    /// the opposing side by replacing its lines with the opposing side's
+    /// corresponding lines (which may be empty, for a pure
+    /// insertion/deletion). Guarded so a read-only `DocContent::History`
+    /// side is never written to.
+    pub fn revert_hunk(&self, is_right: bool, change_idx: usize) {
+        let Some((own_range, other_range)) = self.hunk_ranges(is_right, change_idx)
+        else {
+            return;
+        };
+        self.splice_hunk(!is_right, other_range, is_right, own_range);
+    }
+
+    /// Applies hunk `change_idx` from `is_right`'s side onto the opposing
+    /// side, overwriting the opposing side's lines with this side's. Guarded
+    /// so a read-only `DocContent::History` side is never written to.
+    pub fn apply_hunk(&self, is_right: bool, change_idx: usize) {
+        let Some((own_range, other_range)) = self.hunk_ranges(is_right, change_idx)
+        else {
+            return;
+        };
+        self.splice_hunk(is_right, own_range, !is_right, other_range);
+    }
+
+    /// For hunk `change_idx` on `is_right`'s side, returns `(own_range,
+    /// other_range)`: this side's line range for the hunk, and the opposing
+    /// side's corresponding range. A change block is this hunk plus an
+    /// adjacent entry for the other side, if there is one (an adjacent
+    /// `Left` for a `Right` entry, or vice versa). A lone entry (pure
+    /// insertion/deletion) has no counterpart lines, so its opposing range
+    /// is the empty position it was inserted/deleted at.
+    fn hunk_ranges(
+        &self,
+        is_right: bool,
+        change_idx: usize,
+    ) -> Option<(Range<usize>, Range<usize>)> {
+        let changes = self.changes();
+        let change = changes.get(change_idx)?;
+
+        let own_range = match change {
+            DiffLines::Left(range) if !is_right => range.clone(),
+            DiffLines::Right(range) if is_right => range.clone(),
+            _ => return None,
+        };
+
+        let neighbor = |idx: usize| match (is_right, changes.get(idx)) {
+            (true, Some(DiffLines::Left(range))) => Some(range.clone()),
+            (false, Some(DiffLines::Right(range))) => Some(range.clone()),
+            _ => None,
+        };
+        let other_range = if change_idx > 0 {
+            neighbor(change_idx - 1)
+        } else {
+            None
+        }
+        .or_else(|| neighbor(change_idx + 1))
+        .unwrap_or_else(|| {
+            let edge = changes
+                .iter()
+                .take(change_idx)
+                .rev()
+                .find_map(|c| match (is_right, c) {
+                    (true, DiffLines::Left(r) | DiffLines::Both(r, _) | DiffLines::Skip(r, _)) => {
+                        Some(r.end)
+                    }
+                    (false, DiffLines::Right(r) | DiffLines::Both(_, r) | DiffLines::Skip(_, r)) => {
+                        Some(r.end)
+                    }
+                    _ => None,
+                })
+                .unwrap_or(0);
+            edge..edge
+        });
+
+        Some((own_range, other_range))
+    }
+
+    /// Copies `source_range` lines from the `source_is_right` side into
+    /// `target_range` on the other side. Guarded so a read-only
+    /// `DocContent::History` side, or a synthesized "no document" side, is
+    /// never written to.
+    fn splice_hunk(
+        &self,
+        source_is_right: bool,
+        source_range: Range<usize>,
+        target_is_right: bool,
+        target_range: Range<usize>,
+    ) {
+        let source = if source_is_right { self.right } else { self.left };
+        let target = if target_is_right { self.right } else { self.left };
+
+        let target_is_missing = self.missing_side == Some(target_is_right);
+        let is_read_only = target_is_missing
+            || target.with_untracked(|editor| {
+                editor
+                    .doc
+                    .with_untracked(|doc| matches!(doc.content, DocContent::History(_)))
+            });
+        if is_read_only {
+            return;
+        }
+
+        let source_text = source.with_untracked(|editor| {
+            editor
+                .doc
+                .with_untracked(|doc| line_range_text(doc.buffer().text(), source_range))
+        });
+
+        target.with_untracked(|editor| {
+            editor.doc.with_untracked(|doc| {
+                let buffer = doc.buffer();
+                let start = buffer.offset_of_line(target_range.start);
+                let end = if target_range.end >= buffer.num_lines() {
+                    buffer.len()
+                } else {
+                    buffer.offset_of_line(target_range.end)
+                };
+                doc.do_edit(
+                    &Selection::region(start, end),
+                    &source_text,
+                    EditType::Other,
                 );
-                send(changes.map(im::Vector::from));
             });
         });
     }
 }
+
+/// Result of a single diff computation: the line-level hunks plus the
+/// intra-line highlight spans for each side, keyed by line number.
+struct DiffResult {
+    changes: im::Vector<DiffLines>,
+    left_line_changes: im::HashMap<usize, Vec<Range<usize>>>,
+    right_line_changes: im::HashMap<usize, Vec<Range<usize>>>,
+}
+
+/// A line on one side of a diff, paired with its counterpart on the other
+/// side when it has one. `(Some(l), Some(r))` is an unchanged pair, while a
+/// `None` on either side means the line only exists on the other side.
+type LineAlignment = Vec<(Option<usize>, Option<usize>)>;
+
+/// Runs the plain Myers `rope_diff`. When whitespace is being ignored, the
+/// ropes fed to it are rebuilt from normalized line content first, so the
+/// comparison ignores whitespace while the returned ranges still index into
+/// the same line numbers as the originals.
+fn myers_diff(
+    left_rope: &Rope,
+    right_rope: &Rope,
+    rev: u64,
+    atomic_rev: Arc<atomic::AtomicU64>,
+    context_lines: Option<usize>,
+    whitespace: WhitespaceDiffMode,
+) -> Option<Vec<DiffLines>> {
+    if whitespace == WhitespaceDiffMode::IgnoreNone {
+        return rope_diff(
+            left_rope.clone(),
+            right_rope.clone(),
+            rev,
+            atomic_rev,
+            context_lines,
+        );
+    }
+
+    let left_lines = comparison_lines(left_rope, whitespace);
+    let right_lines = comparison_lines(right_rope, whitespace);
+    let left_normalized = Rope::from(left_lines.join("\n"));
+    let right_normalized = Rope::from(right_lines.join("\n"));
+    rope_diff(left_normalized, right_normalized, rev, atomic_rev, context_lines)
+}
+
+/// Computes a line-level diff using the patience diff algorithm: lines that
+/// occur exactly once on both sides are used as anchors, and the algorithm
+/// recurses on the gaps between anchors, falling back to the existing
+/// `rope_diff` Myers routine for any gap that has no unique anchors.
+///
+/// This materializes each side's lines into a `Vec<Cow<str>>` up front (via
+/// [`comparison_lines`]) rather than operating directly on `RopeText` line
+/// iterators, and the Myers fallback rebuilds whole sub-ropes for the gap it
+/// covers — so despite reading lines through `RopeText`, this is not
+/// zero-copy.
+fn patience_diff(
+    left_rope: &Rope,
+    right_rope: &Rope,
+    rev: u64,
+    atomic_rev: Arc<atomic::AtomicU64>,
+    context_lines: Option<usize>,
+    whitespace: WhitespaceDiffMode,
+) -> Option<Vec<DiffLines>> {
+    let left_lines = comparison_lines(left_rope, whitespace);
+    let right_lines = comparison_lines(right_rope, whitespace);
+
+    let alignment = patience_range(
+        &left_lines,
+        &right_lines,
+        0..left_lines.len(),
+        0..right_lines.len(),
+        rev,
+        &atomic_rev,
+    )?;
+
+    Some(coalesce_alignment(alignment, context_lines))
+}
+
+/// A rope's lines with each one's own trailing line ending (if any)
+/// stripped. `RopeText::line_content` includes the `\n`, which would
+/// otherwise have to be re-added consistently by every caller that
+/// reconstructs a sub-rope from these lines via `"\n".join(..)`.
+fn line_contents(rope: &Rope) -> Vec<Cow<str>> {
+    (0..rope.num_lines())
+        .map(|line| strip_trailing_newline(rope.line_content(line)))
+        .collect()
+}
+
+fn strip_trailing_newline(line: Cow<str>) -> Cow<str> {
+    if !line.ends_with('\n') {
+        return line;
+    }
+    match line {
+        Cow::Borrowed(s) => Cow::Borrowed(&s[..s.len() - 1]),
+        Cow::Owned(mut s) => {
+            s.pop();
+            Cow::Owned(s)
+        }
+    }
+}
+
+/// Which whitespace differences to ignore when deciding whether two lines
+/// are "the same" for diffing purposes. Normalization only affects the
+/// comparison: the original rope ranges reported in `DiffLines` are
+/// untouched, so the editor view still renders the real text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WhitespaceDiffMode {
+    /// Compare lines as-is.
+    IgnoreNone,
+    /// Ignore trailing (end-of-line) whitespace.
+    IgnoreTrailing,
+    /// Collapse every run of whitespace down to a single space, and drop
+    /// leading and trailing whitespace.
+    IgnoreAll,
+}
+
+impl Default for WhitespaceDiffMode {
+    fn default() -> Self {
+        WhitespaceDiffMode::IgnoreNone
+    }
+}
+
+fn normalize_for_diff(line: Cow<str>, whitespace: WhitespaceDiffMode) -> Cow<str> {
+    match whitespace {
+        WhitespaceDiffMode::IgnoreNone => line,
+        WhitespaceDiffMode::IgnoreTrailing => {
+            Cow::Owned(line.trim_end().to_string())
+        }
+        WhitespaceDiffMode::IgnoreAll => {
+            Cow::Owned(line.split_whitespace().collect::<Vec<_>>().join(" "))
+        }
+    }
+}
+
+/// Line contents normalized for comparison according to `whitespace`. Used
+/// only to decide equality; the original text is read separately whenever
+/// it needs to be displayed (e.g. intra-line diffing).
+fn comparison_lines(rope: &Rope, whitespace: WhitespaceDiffMode) -> Vec<Cow<str>> {
+    line_contents(rope)
+        .into_iter()
+        .map(|line| normalize_for_diff(line, whitespace))
+        .collect()
+}
+
+/// The text of a line range, including the trailing newline of every line
+/// in it (only the rope's final line has none), suitable for splicing into
+/// another document in place of an equivalent line range.
+fn line_range_text(rope: &Rope, line_range: Range<usize>) -> String {
+    if line_range.is_empty() {
+        return String::new();
+    }
+    let start = rope.offset_of_line(line_range.start);
+    let end = if line_range.end >= rope.num_lines() {
+        rope.len()
+    } else {
+        rope.offset_of_line(line_range.end)
+    };
+    rope.slice_to_cow(start..end).into_owned()
+}
+
+fn patience_range(
+    left_lines: &[Cow<str>],
+    right_lines: &[Cow<str>],
+    left_range: Range<usize>,
+    right_range: Range<usize>,
+    rev: u64,
+    atomic_rev: &Arc<atomic::AtomicU64>,
+) -> Option<LineAlignment> {
+    if atomic_rev.load(atomic::Ordering::Acquire) != rev {
+        return None;
+    }
+
+    // Trim the common prefix.
+    let mut prefix = 0;
+    while prefix < left_range.len()
+        && prefix < right_range.len()
+        && left_lines[left_range.start + prefix]
+            == right_lines[right_range.start + prefix]
+    {
+        prefix += 1;
+    }
+
+    // Trim the common suffix, without overlapping the prefix we already took.
+    let mut suffix = 0;
+    while suffix < left_range.len() - prefix
+        && suffix < right_range.len() - prefix
+        && left_lines[left_range.end - 1 - suffix]
+            == right_lines[right_range.end - 1 - suffix]
+    {
+        suffix += 1;
+    }
+
+    let mut alignment = LineAlignment::new();
+    for i in 0..prefix {
+        alignment.push((
+            Some(left_range.start + i),
+            Some(right_range.start + i),
+        ));
+    }
+
+    let inner_left = left_range.start + prefix..left_range.end - suffix;
+    let inner_right = right_range.start + prefix..right_range.end - suffix;
+
+    if !inner_left.is_empty() || !inner_right.is_empty() {
+        let anchors = unique_anchors(
+            left_lines,
+            right_lines,
+            inner_left.clone(),
+            inner_right.clone(),
+        );
+
+        if anchors.is_empty() {
+            alignment.extend(myers_fallback(
+                left_lines,
+                right_lines,
+                inner_left,
+                inner_right,
+                rev,
+                atomic_rev,
+            )?);
+        } else {
+            let mut prev_left = inner_left.start;
+            let mut prev_right = inner_right.start;
+            for (anchor_left, anchor_right) in anchors {
+                alignment.extend(patience_range(
+                    left_lines,
+                    right_lines,
+                    prev_left..anchor_left,
+                    prev_right..anchor_right,
+                    rev,
+                    atomic_rev,
+                )?);
+                alignment.push((Some(anchor_left), Some(anchor_right)));
+                prev_left = anchor_left + 1;
+                prev_right = anchor_right + 1;
+            }
+            alignment.extend(patience_range(
+                left_lines,
+                right_lines,
+                prev_left..inner_left.end,
+                prev_right..inner_right.end,
+                rev,
+                atomic_rev,
+            )?);
+        }
+    }
+
+    for i in 0..suffix {
+        alignment.push((
+            Some(left_range.end - suffix + i),
+            Some(right_range.end - suffix + i),
+        ));
+    }
+
+    Some(alignment)
+}
+
+/// Finds lines that occur exactly once on each side within the given
+/// ranges, matches them up by content, and keeps the strictly monotonic
+/// subsequence of matches (by longest increasing subsequence over the
+/// right-hand indices) so the surviving pairs can be used as anchors.
+fn unique_anchors(
+    left_lines: &[Cow<str>],
+    right_lines: &[Cow<str>],
+    left_range: Range<usize>,
+    right_range: Range<usize>,
+) -> Vec<(usize, usize)> {
+    let mut left_counts: HashMap<&str, (usize, usize)> = HashMap::new();
+    for i in left_range {
+        let entry = left_counts
+            .entry(left_lines[i].as_ref())
+            .or_insert((0, i));
+        entry.0 += 1;
+        entry.1 = i;
+    }
+
+    let mut right_counts: HashMap<&str, (usize, usize)> = HashMap::new();
+    for i in right_range {
+        let entry = right_counts
+            .entry(right_lines[i].as_ref())
+            .or_insert((0, i));
+        entry.0 += 1;
+        entry.1 = i;
+    }
+
+    let mut pairs: Vec<(usize, usize)> = left_counts
+        .into_iter()
+        .filter(|(_, (count, _))| *count == 1)
+        .filter_map(|(content, (_, left_idx))| {
+            let (right_count, right_idx) = right_counts.get(content)?;
+            (*right_count == 1).then_some((left_idx, *right_idx))
+        })
+        .collect();
+    pairs.sort_unstable_by_key(|&(left_idx, _)| left_idx);
+
+    longest_increasing_subsequence(&pairs)
+}
+
+/// Longest increasing subsequence over `pairs` ordered by `.1` (the
+/// right-hand index), via patience sorting: O(n log n) with predecessor
+/// tracking to reconstruct the actual subsequence.
+fn longest_increasing_subsequence(pairs: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut pile_tops: Vec<usize> = Vec::new();
+    let mut predecessors: Vec<Option<usize>> = vec![None; pairs.len()];
+
+    for (i, &(_, right_idx)) in pairs.iter().enumerate() {
+        let pos = pile_tops.partition_point(|&p| pairs[p].1 < right_idx);
+        if pos > 0 {
+            predecessors[i] = Some(pile_tops[pos - 1]);
+        }
+        if pos == pile_tops.len() {
+            pile_tops.push(i);
+        } else {
+            pile_tops[pos] = i;
+        }
+    }
+
+    let mut lis = Vec::with_capacity(pile_tops.len());
+    let mut cur = pile_tops.last().copied();
+    while let Some(i) = cur {
+        lis.push(pairs[i]);
+        cur = predecessors[i];
+    }
+    lis.reverse();
+    lis
+}
+
+/// Runs the existing Myers-style `rope_diff` over a sub-range of lines,
+/// then remaps its (range-relative) results back into absolute line
+/// indices, for use as the patience diff's fallback when a gap has no
+/// unique anchors. Takes already-normalized line content (rather than the
+/// original ropes) so whitespace-insensitive modes apply here too.
+fn myers_fallback(
+    left_lines: &[Cow<str>],
+    right_lines: &[Cow<str>],
+    left_range: Range<usize>,
+    right_range: Range<usize>,
+    rev: u64,
+    atomic_rev: &Arc<atomic::AtomicU64>,
+) -> Option<LineAlignment> {
+    if left_range.is_empty() && right_range.is_empty() {
+        return Some(LineAlignment::new());
+    }
+
+    let left_sub = Rope::from(left_lines[left_range.clone()].join("\n"));
+    let right_sub = Rope::from(right_lines[right_range.clone()].join("\n"));
+
+    let diff_lines =
+        rope_diff(left_sub, right_sub, rev, atomic_rev.clone(), None)?;
+
+    Some(diff_lines_to_alignment(
+        &diff_lines,
+        left_range.start,
+        right_range.start,
+    ))
+}
+
+fn diff_lines_to_alignment(
+    diff_lines: &[DiffLines],
+    left_offset: usize,
+    right_offset: usize,
+) -> LineAlignment {
+    let mut alignment = LineAlignment::new();
+    for diff_line in diff_lines {
+        match diff_line {
+            DiffLines::Left(range) => {
+                for i in range.clone() {
+                    alignment.push((Some(left_offset + i), None));
+                }
+            }
+            DiffLines::Right(range) => {
+                for i in range.clone() {
+                    alignment.push((None, Some(right_offset + i)));
+                }
+            }
+            DiffLines::Both(left, right) | DiffLines::Skip(left, right) => {
+                for (l, r) in left.clone().zip(right.clone()) {
+                    alignment
+                        .push((Some(left_offset + l), Some(right_offset + r)));
+                }
+            }
+        }
+    }
+    alignment
+}
+
+/// Groups a flat line alignment into `DiffLines` ranges, collapsing runs of
+/// unchanged lines longer than `2 * context_lines` into `Skip` so that only
+/// `context_lines` of surrounding context are kept around each change.
+fn coalesce_alignment(
+    alignment: LineAlignment,
+    context_lines: Option<usize>,
+) -> Vec<DiffLines> {
+    #[derive(PartialEq)]
+    enum Kind {
+        Left,
+        Right,
+        Both,
+    }
+
+    let mut groups: Vec<(Kind, Range<usize>, Range<usize>)> = Vec::new();
+    for (left, right) in alignment {
+        let kind = match (left, right) {
+            (Some(_), Some(_)) => Kind::Both,
+            (Some(_), None) => Kind::Left,
+            (None, Some(_)) => Kind::Right,
+            (None, None) => continue,
+        };
+
+        if let Some(last) = groups.last_mut() {
+            if last.0 == kind {
+                if let Some(l) = left {
+                    last.1.end = l + 1;
+                }
+                if let Some(r) = right {
+                    last.2.end = r + 1;
+                }
+                continue;
+            }
+        }
+
+        groups.push((
+            kind,
+            left.map(|l| l..l + 1).unwrap_or(0..0),
+            right.map(|r| r..r + 1).unwrap_or(0..0),
+        ));
+    }
+
+    let context = context_lines.unwrap_or(usize::MAX);
+    let last_group = groups.len().saturating_sub(1);
+    let mut result = Vec::with_capacity(groups.len());
+    for (i, (kind, left, right)) in groups.into_iter().enumerate() {
+        match kind {
+            Kind::Left => result.push(DiffLines::Left(left)),
+            Kind::Right => result.push(DiffLines::Right(right)),
+            Kind::Both => {
+                let len = left.len();
+                let keep_all = i == 0 || i == last_group;
+                if len <= context.saturating_mul(2) || (keep_all && len <= context) {
+                    result.push(DiffLines::Both(left, right));
+                } else if keep_all {
+                    // Only one edge of this run borders a change; keep
+                    // context lines on that side and collapse the rest.
+                    if i == 0 {
+                        let split = len - context;
+                        result.push(DiffLines::Skip(
+                            left.start..left.start + split,
+                            right.start..right.start + split,
+                        ));
+                        result.push(DiffLines::Both(
+                            left.start + split..left.end,
+                            right.start + split..right.end,
+                        ));
+                    } else {
+                        result.push(DiffLines::Both(
+                            left.start..left.start + context,
+                            right.start..right.start + context,
+                        ));
+                        result.push(DiffLines::Skip(
+                            left.start + context..left.end,
+                            right.start + context..right.end,
+                        ));
+                    }
+                } else {
+                    result.push(DiffLines::Both(
+                        left.start..left.start + context,
+                        right.start..right.start + context,
+                    ));
+                    result.push(DiffLines::Skip(
+                        left.start + context..left.end - context,
+                        right.start + context..right.end - context,
+                    ));
+                    result.push(DiffLines::Both(
+                        left.end - context..left.end,
+                        right.end - context..right.end,
+                    ));
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Plain longest-common-subsequence line diff: dynamic-programming LCS over
+/// the full line ranges, with no anchor-based recursion.
+fn lcs_diff(
+    left_rope: &Rope,
+    right_rope: &Rope,
+    rev: u64,
+    atomic_rev: Arc<atomic::AtomicU64>,
+    context_lines: Option<usize>,
+    whitespace: WhitespaceDiffMode,
+) -> Option<Vec<DiffLines>> {
+    let left_lines = comparison_lines(left_rope, whitespace);
+    let right_lines = comparison_lines(right_rope, whitespace);
+
+    let alignment =
+        lcs_alignment(&left_lines, &right_lines, rev, &atomic_rev)?;
+    Some(coalesce_alignment(alignment, context_lines))
+}
+
+fn lcs_alignment(
+    left_lines: &[Cow<str>],
+    right_lines: &[Cow<str>],
+    rev: u64,
+    atomic_rev: &Arc<atomic::AtomicU64>,
+) -> Option<LineAlignment> {
+    let n = left_lines.len();
+    let m = right_lines.len();
+
+    let mut lengths = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        if atomic_rev.load(atomic::Ordering::Acquire) != rev {
+            return None;
+        }
+        for j in (0..m).rev() {
+            lengths[i][j] = if left_lines[i] == right_lines[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut alignment = LineAlignment::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if left_lines[i] == right_lines[j] {
+            alignment.push((Some(i), Some(j)));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            alignment.push((Some(i), None));
+            i += 1;
+        } else {
+            alignment.push((None, Some(j)));
+            j += 1;
+        }
+    }
+    for i in i..n {
+        alignment.push((Some(i), None));
+    }
+    for j in j..m {
+        alignment.push((None, Some(j)));
+    }
+
+    Some(alignment)
+}
+
+/// Levenshtein (edit-distance) line diff: same dynamic-programming shape as
+/// `lcs_diff`, but substitutions are a valid edit, so a changed line is
+/// reported as a same-position delete+insert pair rather than drifting the
+/// alignment.
+fn levenshtein_diff(
+    left_rope: &Rope,
+    right_rope: &Rope,
+    rev: u64,
+    atomic_rev: Arc<atomic::AtomicU64>,
+    context_lines: Option<usize>,
+    whitespace: WhitespaceDiffMode,
+) -> Option<Vec<DiffLines>> {
+    let left_lines = comparison_lines(left_rope, whitespace);
+    let right_lines = comparison_lines(right_rope, whitespace);
+
+    let alignment =
+        levenshtein_alignment(&left_lines, &right_lines, rev, &atomic_rev)?;
+    Some(coalesce_alignment(alignment, context_lines))
+}
+
+fn levenshtein_alignment(
+    left_lines: &[Cow<str>],
+    right_lines: &[Cow<str>],
+    rev: u64,
+    atomic_rev: &Arc<atomic::AtomicU64>,
+) -> Option<LineAlignment> {
+    let n = left_lines.len();
+    let m = right_lines.len();
+
+    let mut dist = vec![vec![0usize; m + 1]; n + 1];
+    for (i, row) in dist.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for j in 0..=m {
+        dist[0][j] = j;
+    }
+    for i in 1..=n {
+        if atomic_rev.load(atomic::Ordering::Acquire) != rev {
+            return None;
+        }
+        for j in 1..=m {
+            dist[i][j] = if left_lines[i - 1] == right_lines[j - 1] {
+                dist[i - 1][j - 1]
+            } else {
+                1 + dist[i - 1][j - 1]
+                    .min(dist[i - 1][j])
+                    .min(dist[i][j - 1])
+            };
+        }
+    }
+
+    let mut alignment = LineAlignment::new();
+    let (mut i, mut j) = (n, m);
+    while i > 0 && j > 0 {
+        if left_lines[i - 1] == right_lines[j - 1] {
+            alignment.push((Some(i - 1), Some(j - 1)));
+            i -= 1;
+            j -= 1;
+        } else if dist[i][j] == dist[i - 1][j - 1] + 1 {
+            // Substitution: the lines differ, so report it as a
+            // same-position delete+insert pair rather than a match.
+            alignment.push((Some(i - 1), None));
+            alignment.push((None, Some(j - 1)));
+            i -= 1;
+            j -= 1;
+        } else if dist[i][j] == dist[i - 1][j] + 1 {
+            alignment.push((Some(i - 1), None));
+            i -= 1;
+        } else {
+            alignment.push((None, Some(j - 1)));
+            j -= 1;
+        }
+    }
+    while i > 0 {
+        alignment.push((Some(i - 1), None));
+        i -= 1;
+    }
+    while j > 0 {
+        alignment.push((None, Some(j - 1)));
+        j -= 1;
+    }
+    alignment.reverse();
+
+    Some(alignment)
+}
+
+/// Walks the computed hunks and, for every adjacent `Left`/`Right` change
+/// block, runs a word-level diff between corresponding removed/added lines
+/// so the editor view can render tighter inline highlights instead of
+/// lighting up the whole line.
+fn compute_intraline_changes(
+    changes: &[DiffLines],
+    left_rope: &Rope,
+    right_rope: &Rope,
+) -> (
+    im::HashMap<usize, Vec<Range<usize>>>,
+    im::HashMap<usize, Vec<Range<usize>>>,
+) {
+    let mut left_line_changes = im::HashMap::new();
+    let mut right_line_changes = im::HashMap::new();
+
+    let mut i = 0;
+    while i < changes.len() {
+        let block = match (&changes[i], changes.get(i + 1)) {
+            (DiffLines::Left(left), Some(DiffLines::Right(right))) => {
+                Some((left.clone(), right.clone()))
+            }
+            (DiffLines::Right(right), Some(DiffLines::Left(left))) => {
+                Some((left.clone(), right.clone()))
+            }
+            _ => None,
+        };
+
+        let Some((left_range, right_range)) = block else {
+            i += 1;
+            continue;
+        };
+
+        diff_change_block(
+            left_rope,
+            right_rope,
+            left_range,
+            right_range,
+            &mut left_line_changes,
+            &mut right_line_changes,
+        );
+        i += 2;
+    }
+
+    (left_line_changes, right_line_changes)
+}
+
+fn diff_change_block(
+    left_rope: &Rope,
+    right_rope: &Rope,
+    left_range: Range<usize>,
+    right_range: Range<usize>,
+    left_line_changes: &mut im::HashMap<usize, Vec<Range<usize>>>,
+    right_line_changes: &mut im::HashMap<usize, Vec<Range<usize>>>,
+) {
+    let paired = left_range.len().min(right_range.len());
+    for offset in 0..paired {
+        let left_line_no = left_range.start + offset;
+        let right_line_no = right_range.start + offset;
+        let left_line = left_rope.line_content(left_line_no);
+        let right_line = right_rope.line_content(right_line_no);
+
+        let (delete_spans, insert_spans) =
+            word_diff_line(&left_line, &right_line);
+        if !delete_spans.is_empty() {
+            left_line_changes.insert(left_line_no, delete_spans);
+        }
+        if !insert_spans.is_empty() {
+            right_line_changes.insert(right_line_no, insert_spans);
+        }
+    }
+
+    // Unequal line counts leave surplus lines with no counterpart to diff
+    // against; highlight them in full rather than word-diffing nothing.
+    // `line_content` includes the trailing newline, which isn't part of the
+    // visible text, so the span is clamped to exclude it.
+    for left_line_no in left_range.start + paired..left_range.end {
+        let len = strip_trailing_newline(left_rope.line_content(left_line_no)).len();
+        left_line_changes.insert(left_line_no, vec![0..len]);
+    }
+    for right_line_no in right_range.start + paired..right_range.end {
+        let len =
+            strip_trailing_newline(right_rope.line_content(right_line_no)).len();
+        right_line_changes.insert(right_line_no, vec![0..len]);
+    }
+}
+
+/// Diffs two lines word-by-word (splitting on Unicode word boundaries) via
+/// LCS over the tokens, returning the byte ranges in each line that were
+/// not matched: deletions on the left, insertions on the right.
+fn word_diff_line(left: &str, right: &str) -> (Vec<Range<usize>>, Vec<Range<usize>>) {
+    let left_tokens: Vec<(usize, &str)> = left.split_word_bound_indices().collect();
+    let right_tokens: Vec<(usize, &str)> =
+        right.split_word_bound_indices().collect();
+
+    let n = left_tokens.len();
+    let m = right_tokens.len();
+    let mut lengths = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[i][j] = if left_tokens[i].1 == right_tokens[j].1 {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut delete_spans = Vec::new();
+    let mut insert_spans = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if left_tokens[i].1 == right_tokens[j].1 {
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            let (start, token) = left_tokens[i];
+            delete_spans.push(start..start + token.len());
+            i += 1;
+        } else {
+            let (start, token) = right_tokens[j];
+            insert_spans.push(start..start + token.len());
+            j += 1;
+        }
+    }
+    for &(start, token) in &left_tokens[i..] {
+        delete_spans.push(start..start + token.len());
+    }
+    for &(start, token) in &right_tokens[j..] {
+        insert_spans.push(start..start + token.len());
+    }
+
+    (merge_adjacent_spans(delete_spans), merge_adjacent_spans(insert_spans))
+}
+
+fn merge_adjacent_spans(spans: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(spans.len());
+    for span in spans {
+        if let Some(last) = merged.last_mut() {
+            if last.end == span.start {
+                last.end = span.end;
+                continue;
+            }
+        }
+        merged.push(span);
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cow_lines(lines: &[&str]) -> Vec<Cow<'static, str>> {
+        lines.iter().map(|s| Cow::Owned(s.to_string())).collect()
+    }
+
+    #[test]
+    fn unique_anchors_handles_empty_side() {
+        let left = cow_lines(&[]);
+        let right = cow_lines(&["a"]);
+        assert!(unique_anchors(&left, &right, 0..0, 0..1).is_empty());
+    }
+
+    #[test]
+    fn unique_anchors_ignores_duplicated_lines() {
+        let left = cow_lines(&["a", "a", "b"]);
+        let right = cow_lines(&["a", "b"]);
+        // "a" occurs twice on the left, so it's not a unique anchor; only
+        // "b" is unique on both sides.
+        let anchors = unique_anchors(&left, &right, 0..3, 0..2);
+        assert_eq!(anchors, vec![(2, 1)]);
+    }
+
+    #[test]
+    fn longest_increasing_subsequence_keeps_monotonic_run() {
+        let pairs = vec![(0, 2), (1, 0), (2, 1), (3, 3)];
+        let lis = longest_increasing_subsequence(&pairs);
+        assert_eq!(lis, vec![(1, 0), (2, 1), (3, 3)]);
+    }
+
+    #[test]
+    fn longest_increasing_subsequence_empty_input() {
+        assert!(longest_increasing_subsequence(&[]).is_empty());
+    }
+
+    #[test]
+    fn patience_range_handles_both_sides_empty() {
+        let left: Vec<Cow<str>> = Vec::new();
+        let right: Vec<Cow<str>> = Vec::new();
+        let atomic_rev = Arc::new(atomic::AtomicU64::new(0));
+        let alignment =
+            patience_range(&left, &right, 0..0, 0..0, 0, &atomic_rev).unwrap();
+        assert!(alignment.is_empty());
+    }
+
+    #[test]
+    fn patience_range_trims_prefix_and_suffix_without_double_counting() {
+        // Both lines are identical, so the prefix trim alone consumes the
+        // whole range; the suffix trim must not also claim an overlapping
+        // line.
+        let left = cow_lines(&["a", "a"]);
+        let right = cow_lines(&["a", "a"]);
+        let atomic_rev = Arc::new(atomic::AtomicU64::new(0));
+        let alignment =
+            patience_range(&left, &right, 0..2, 0..2, 0, &atomic_rev).unwrap();
+        assert_eq!(alignment, vec![(Some(0), Some(0)), (Some(1), Some(1))]);
+    }
+
+    #[test]
+    fn patience_range_single_shared_line_counted_once() {
+        let left = cow_lines(&["a"]);
+        let right = cow_lines(&["a"]);
+        let atomic_rev = Arc::new(atomic::AtomicU64::new(0));
+        let alignment =
+            patience_range(&left, &right, 0..1, 0..1, 0, &atomic_rev).unwrap();
+        assert_eq!(alignment, vec![(Some(0), Some(0))]);
+    }
+
+    #[test]
+    fn patience_range_falls_back_to_myers_with_no_unique_anchors() {
+        // Every line is duplicated on each side, so there are no unique
+        // anchors and the gap must fall back to the Myers routine instead
+        // of getting stuck.
+        let left = cow_lines(&["x", "x"]);
+        let right = cow_lines(&["y", "y"]);
+        let atomic_rev = Arc::new(atomic::AtomicU64::new(0));
+        let alignment =
+            patience_range(&left, &right, 0..2, 0..2, 0, &atomic_rev).unwrap();
+        let left_only = alignment
+            .iter()
+            .filter(|(l, r)| l.is_some() && r.is_none())
+            .count();
+        let right_only = alignment
+            .iter()
+            .filter(|(l, r)| l.is_none() && r.is_some())
+            .count();
+        assert_eq!(left_only, 2);
+        assert_eq!(right_only, 2);
+    }
+
+    #[test]
+    fn patience_diff_fallback_handles_real_rope_line_endings() {
+        // `RopeText::line_content` includes each line's own trailing `\n`.
+        // Unlike the other fallback test above (which builds lines by hand
+        // with no terminator), this one goes through the real `Rope` so
+        // `comparison_lines`' newline-stripping is actually exercised: if
+        // it ever stopped stripping, or `myers_fallback` went back to
+        // reconstructing sub-ropes without accounting for that, the
+        // fallback would report line indices past the end of each side.
+        let left_rope = Rope::from("x\nx\n".to_string());
+        let right_rope = Rope::from("y\ny\n".to_string());
+        let atomic_rev = Arc::new(atomic::AtomicU64::new(0));
+        let changes = patience_diff(
+            &left_rope,
+            &right_rope,
+            0,
+            atomic_rev,
+            None,
+            WhitespaceDiffMode::IgnoreNone,
+        )
+        .unwrap();
+
+        let max_end = |range_of: fn(&DiffLines) -> Option<(Range<usize>, Range<usize>)>| {
+            changes.iter().filter_map(range_of).fold((0, 0), |acc, (l, r)| {
+                (acc.0.max(l.end), acc.1.max(r.end))
+            })
+        };
+        let (max_left, max_right) = max_end(|change| match change {
+            DiffLines::Left(r) => Some((r.clone(), 0..0)),
+            DiffLines::Right(r) => Some((0..0, r.clone())),
+            DiffLines::Both(l, r) | DiffLines::Skip(l, r) => {
+                Some((l.clone(), r.clone()))
+            }
+        });
+
+        assert!(
+            max_left <= left_rope.num_lines(),
+            "left line index {max_left} exceeds the side's real line count {}",
+            left_rope.num_lines()
+        );
+        assert!(
+            max_right <= right_rope.num_lines(),
+            "right line index {max_right} exceeds the side's real line count {}",
+            right_rope.num_lines()
+        );
+    }
+
+    #[test]
+    fn patience_range_stops_on_stale_revision() {
+        let left = cow_lines(&["a"]);
+        let right = cow_lines(&["b"]);
+        // `rev` (0) no longer matches `atomic_rev` (1): the computation is
+        // stale and must bail out rather than diffing.
+        let atomic_rev = Arc::new(atomic::AtomicU64::new(1));
+        assert!(
+            patience_range(&left, &right, 0..1, 0..1, 0, &atomic_rev).is_none()
+        );
+    }
+
+    #[test]
+    fn lcs_alignment_handles_empty_left_side() {
+        let left: Vec<Cow<str>> = Vec::new();
+        let right = cow_lines(&["a", "b"]);
+        let atomic_rev = Arc::new(atomic::AtomicU64::new(0));
+        let alignment = lcs_alignment(&left, &right, 0, &atomic_rev).unwrap();
+        assert_eq!(alignment, vec![(None, Some(0)), (None, Some(1))]);
+    }
+
+    #[test]
+    fn lcs_alignment_handles_unequal_line_counts() {
+        let left = cow_lines(&["a", "b", "c"]);
+        let right = cow_lines(&["a", "c"]);
+        let atomic_rev = Arc::new(atomic::AtomicU64::new(0));
+        let alignment = lcs_alignment(&left, &right, 0, &atomic_rev).unwrap();
+        assert_eq!(
+            alignment,
+            vec![(Some(0), Some(0)), (Some(1), None), (Some(2), Some(1))]
+        );
+    }
+
+    #[test]
+    fn levenshtein_alignment_handles_empty_left_side() {
+        let left: Vec<Cow<str>> = Vec::new();
+        let right = cow_lines(&["a"]);
+        let atomic_rev = Arc::new(atomic::AtomicU64::new(0));
+        let alignment =
+            levenshtein_alignment(&left, &right, 0, &atomic_rev).unwrap();
+        assert_eq!(alignment, vec![(None, Some(0))]);
+    }
+
+    #[test]
+    fn levenshtein_alignment_reports_substitution_as_delete_insert_pair() {
+        let left = cow_lines(&["same", "old"]);
+        let right = cow_lines(&["same", "new"]);
+        let atomic_rev = Arc::new(atomic::AtomicU64::new(0));
+        let alignment =
+            levenshtein_alignment(&left, &right, 0, &atomic_rev).unwrap();
+        // "old"/"new" is a substitution: reported as a delete+insert pair,
+        // never as a false `Both` match.
+        assert!(!alignment.contains(&(Some(1), Some(1))));
+        assert!(alignment.contains(&(Some(1), None)));
+        assert!(alignment.contains(&(None, Some(1))));
+    }
+
+    #[test]
+    fn coalesce_alignment_collapses_long_unchanged_runs() {
+        let alignment: LineAlignment = vec![
+            (Some(0), Some(0)),
+            (Some(1), Some(1)),
+            (Some(2), Some(2)),
+            (Some(3), Some(3)),
+            (Some(4), Some(4)),
+            (Some(5), None),
+        ];
+        let result = coalesce_alignment(alignment, Some(1));
+        // The unchanged run borders the trailing change on its end only, so
+        // only the last `context_lines` before the change are kept.
+        assert_eq!(
+            result,
+            vec![
+                DiffLines::Skip(0..4, 0..4),
+                DiffLines::Both(4..5, 4..5),
+                DiffLines::Left(5..6),
+            ]
+        );
+    }
+
+    #[test]
+    fn coalesce_alignment_keeps_short_runs_whole() {
+        let alignment: LineAlignment =
+            vec![(Some(0), Some(0)), (Some(1), Some(1)), (Some(2), None)];
+        let result = coalesce_alignment(alignment, Some(5));
+        assert_eq!(
+            result,
+            vec![DiffLines::Both(0..2, 0..2), DiffLines::Left(2..3)]
+        );
+    }
+
+    #[test]
+    fn word_diff_line_reports_changed_word_only() {
+        let (delete_spans, insert_spans) = word_diff_line("foo bar", "foo baz");
+        let deleted: Vec<&str> =
+            delete_spans.iter().map(|r| &"foo bar"[r.clone()]).collect();
+        let inserted: Vec<&str> =
+            insert_spans.iter().map(|r| &"foo baz"[r.clone()]).collect();
+        assert_eq!(deleted, vec!["bar"]);
+        assert_eq!(inserted, vec!["baz"]);
+    }
+
+    #[test]
+    fn word_diff_line_empty_inputs() {
+        let (delete_spans, insert_spans) = word_diff_line("", "");
+        assert!(delete_spans.is_empty());
+        assert!(insert_spans.is_empty());
+    }
+
+    #[test]
+    fn merge_adjacent_spans_joins_touching_ranges() {
+        let merged = merge_adjacent_spans(vec![0..3, 3..5, 7..9]);
+        assert_eq!(merged, vec![0..5, 7..9]);
+    }
+
+    #[test]
+    fn merge_adjacent_spans_empty_input() {
+        assert!(merge_adjacent_spans(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn diff_lines_to_alignment_applies_offsets() {
+        let diff_lines = vec![DiffLines::Left(0..2), DiffLines::Right(0..1)];
+        let alignment = diff_lines_to_alignment(&diff_lines, 3, 5);
+        assert_eq!(
+            alignment,
+            vec![(Some(3), None), (Some(4), None), (None, Some(5))]
+        );
+    }
+}